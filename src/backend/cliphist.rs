@@ -0,0 +1,76 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::ClipboardBackend;
+use crate::Error;
+
+/// Shells out to the `cliphist` CLI, the default and original backend.
+pub struct CliphistBackend {
+    cliphist_path: String,
+}
+
+impl CliphistBackend {
+    pub fn new(cliphist_path: String) -> Self {
+        Self { cliphist_path }
+    }
+
+    fn run_with_stdin(&self, args: &[&str], stdin_data: String) -> Result<Vec<u8>, Error> {
+        let child = Command::new(&self.cliphist_path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(Error::CliphistCommandFailed);
+
+        child.and_then(|mut c| {
+            let write_to_stdin = c
+                .stdin
+                .take()
+                .ok_or(Error::StdinError)
+                .and_then(|mut stdin| {
+                    std::thread::spawn(move || {
+                        stdin
+                            .write_all(stdin_data.as_bytes())
+                            .map_err(|_| Error::StdinError)
+                    })
+                    .join()
+                    .map_err(|_| Error::Threaderror)
+                    .and_then(|r| r)
+                });
+            write_to_stdin
+                .and_then(|_| c.wait_with_output().map_err(Error::CliphistCommandFailed))
+                .map(|o| o.stdout)
+        })
+    }
+}
+
+impl ClipboardBackend for CliphistBackend {
+    fn list(&self) -> Result<Vec<(String, String)>, Error> {
+        let output = Command::new(&self.cliphist_path)
+            .args(["list"])
+            .output()
+            .map_err(Error::CliphistCommandFailed)?;
+
+        if !output.status.success() {
+            return Err(Error::CliphistReturnCodeError(
+                output.status.code().unwrap_or(1),
+            ));
+        }
+
+        let content = String::from_utf8_lossy(&output.stdout).into_owned();
+        Ok(content
+            .split('\n')
+            .filter_map(|l| l.split_once('\t'))
+            .map(|(id, entry)| (id.to_string(), entry.to_string()))
+            .collect())
+    }
+
+    fn decode(&self, id: &str) -> Result<Vec<u8>, Error> {
+        self.run_with_stdin(&["decode"], format!("{}\t ", id))
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        self.run_with_stdin(&["delete"], format!("{}\t ", id))
+            .map(|_| ())
+    }
+}
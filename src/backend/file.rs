@@ -0,0 +1,51 @@
+use std::fs;
+
+use super::ClipboardBackend;
+use crate::Error;
+
+/// Reads clipboard history directly from a plain-text file, one entry per
+/// line, for setups that don't run `cliphist`. Blank lines are skipped, and
+/// an entry's 0-based position among the remaining lines is used as its
+/// (volatile) id.
+pub struct FileBackend {
+    path: String,
+}
+
+impl FileBackend {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+
+    fn read_lines(&self) -> Result<Vec<String>, Error> {
+        let content = fs::read_to_string(&self.path).map_err(Error::FileBackendReadError)?;
+        Ok(content
+            .lines()
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect())
+    }
+}
+
+impl ClipboardBackend for FileBackend {
+    fn list(&self) -> Result<Vec<(String, String)>, Error> {
+        Ok(self
+            .read_lines()?
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| (i.to_string(), entry))
+            .collect())
+    }
+
+    fn decode(&self, id: &str) -> Result<Vec<u8>, Error> {
+        let index: usize = id.parse().map_err(|_| Error::FileBackendInvalidId)?;
+        self.read_lines()?
+            .into_iter()
+            .nth(index)
+            .map(|entry| entry.into_bytes())
+            .ok_or(Error::FileBackendInvalidId)
+    }
+
+    fn supports_delete(&self) -> bool {
+        false
+    }
+}
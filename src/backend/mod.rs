@@ -0,0 +1,57 @@
+mod cliphist;
+mod file;
+
+pub use cliphist::CliphistBackend;
+pub use file::FileBackend;
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// A source of clipboard history entries, decoupling the fuzzy-matching and
+/// `Match`-building code in `lib.rs` from how entries are actually listed,
+/// decoded and removed.
+pub trait ClipboardBackend {
+    /// Lists the current history as `(id, entry)` pairs, in the backend's
+    /// natural (usually most-recent-first) order.
+    fn list(&self) -> Result<Vec<(String, String)>, Error>;
+
+    /// Resolves an id returned by `list` to the raw clipboard content.
+    fn decode(&self, id: &str) -> Result<Vec<u8>, Error>;
+
+    /// Removes an entry from the backend's history, if supported.
+    fn delete(&self, _id: &str) -> Result<(), Error> {
+        Err(Error::UnsupportedOperation)
+    }
+
+    /// Whether `delete` is actually implemented, rather than just returning
+    /// `Err(Error::UnsupportedOperation)`. Lets callers hide delete-dependent
+    /// features (like bulk-edit) instead of offering them and failing.
+    fn supports_delete(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    Cliphist,
+    File,
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Cliphist
+    }
+}
+
+pub fn build_backend(
+    kind: BackendKind,
+    cliphist_path: &str,
+    history_file: &str,
+) -> Box<dyn ClipboardBackend> {
+    match kind {
+        BackendKind::Cliphist => Box::new(CliphistBackend::new(cliphist_path.to_string())),
+        BackendKind::File => Box::new(FileBackend::new(history_file.to_string())),
+    }
+}
@@ -1,10 +1,16 @@
 use abi_stable::std_types::{ROption, RString, RVec};
 use anyrun_plugin::*;
 use fuzzy_matcher::FuzzyMatcher;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::io::Write;
-use std::process::{Command, Stdio};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod backend;
+
+use backend::{build_backend, BackendKind, ClipboardBackend};
 
 #[derive(Deserialize)]
 struct Config {
@@ -14,6 +20,16 @@ struct Config {
     cliphist_path: String,
     #[serde(default = "prefix")]
     prefix: String,
+    #[serde(default = "edit_keyword")]
+    edit_keyword: String,
+    #[serde(default = "half_life_hours")]
+    half_life_hours: f64,
+    #[serde(default)]
+    backend: BackendKind,
+    #[serde(default = "history_file")]
+    history_file: String,
+    #[serde(default = "terminal")]
+    terminal: String,
 }
 
 fn max_entries() -> usize {
@@ -28,13 +44,157 @@ fn prefix() -> String {
     "".into()
 }
 
+fn edit_keyword() -> String {
+    ":edit".into()
+}
+
+fn half_life_hours() -> f64 {
+    72.0
+}
+
+fn history_file() -> String {
+    "".into()
+}
+
+fn terminal() -> String {
+    std::env::var("TERMINAL").unwrap_or_else(|_| "xterm".into())
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             max_entries: max_entries(),
             cliphist_path: cliphist_path(),
             prefix: prefix(),
+            edit_keyword: edit_keyword(),
+            half_life_hours: half_life_hours(),
+            backend: BackendKind::default(),
+            history_file: history_file(),
+            terminal: terminal(),
+        }
+    }
+}
+
+const EDIT_MATCH_ID: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct FrecencyEntry {
+    access_count: u64,
+    last_used: u64,
+}
+
+type FrecencyStore = HashMap<u64, FrecencyEntry>;
+
+/// Maps a `cliphist_id` to the content hash it was last seen to decode to.
+/// Persisted so that ranking by frecency doesn't require re-decoding the
+/// whole history on every cold start (anyrun is a short-lived process, so
+/// every invocation is a cold start) - only ids that have never been copied
+/// (and so have no frecency entry to look up anyway) miss this cache.
+type ContentKeyStore = HashMap<String, u64>;
+
+/// FNV-1a over the decoded clip bytes. Hand-rolled (rather than
+/// `std::hash::Hasher`'s `DefaultHasher`) because this value is persisted in
+/// `cliphist_frecency.ron` as a stable key, and `DefaultHasher`'s algorithm
+/// is explicitly not guaranteed to stay the same across Rust releases.
+fn content_key(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Looks up the frecency key for a clip from the persisted/cached
+/// `cliphist_id -> content hash` mapping. Entries only land in this cache
+/// when a clip is actually copied (see `handler`), so this never decodes -
+/// a clip with no recorded usage simply has no score to look up.
+fn content_key_for(state: &State, cliphist_id: &str) -> Option<u64> {
+    state.content_keys.borrow().get(cliphist_id).copied()
+}
+
+/// Drops mappings for ids that no longer appear in the current
+/// `cliphist list`, so the persisted cache doesn't grow forever as history
+/// rotates. Cheap: just a set of ids already returned by `list`, no decode.
+fn reconcile_content_keys(store: &mut ContentKeyStore, live_ids: &std::collections::HashSet<&str>) {
+    store.retain(|id, _| live_ids.contains(id.as_str()));
+}
+
+/// Drops frecency entries whose content key is no longer referenced by any
+/// surviving `cliphist_id`, i.e. entries for clips no longer in
+/// `cliphist list`.
+fn reconcile_frecency(frecency: &mut FrecencyStore, content_keys: &ContentKeyStore) {
+    let live_keys: std::collections::HashSet<u64> = content_keys.values().copied().collect();
+    frecency.retain(|key, _| live_keys.contains(key));
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn frecency_store_path(config_dir: &str) -> String {
+    format!("{}/cliphist_frecency.ron", config_dir)
+}
+
+fn load_frecency(config_dir: &str) -> FrecencyStore {
+    match fs::read_to_string(frecency_store_path(config_dir)) {
+        Ok(content) => ron::from_str(&content).unwrap_or_else(|why| {
+            eprintln!("Error parsing cliphist frecency store: {}", why);
+            FrecencyStore::new()
+        }),
+        Err(_) => FrecencyStore::new(),
+    }
+}
+
+fn save_frecency(config_dir: &str, store: &FrecencyStore) {
+    match ron::to_string(store) {
+        Ok(serialized) => {
+            if let Err(why) = fs::write(frecency_store_path(config_dir), serialized) {
+                eprintln!("Error writing cliphist frecency store: {}", why);
+            }
+        }
+        Err(why) => eprintln!("Error serializing cliphist frecency store: {}", why),
+    }
+}
+
+fn content_keys_store_path(config_dir: &str) -> String {
+    format!("{}/cliphist_content_keys.ron", config_dir)
+}
+
+fn load_content_keys(config_dir: &str) -> ContentKeyStore {
+    match fs::read_to_string(content_keys_store_path(config_dir)) {
+        Ok(content) => ron::from_str(&content).unwrap_or_else(|why| {
+            eprintln!("Error parsing cliphist content key cache: {}", why);
+            ContentKeyStore::new()
+        }),
+        Err(_) => ContentKeyStore::new(),
+    }
+}
+
+fn save_content_keys(config_dir: &str, store: &ContentKeyStore) {
+    match ron::to_string(store) {
+        Ok(serialized) => {
+            if let Err(why) = fs::write(content_keys_store_path(config_dir), serialized) {
+                eprintln!("Error writing cliphist content key cache: {}", why);
+            }
+        }
+        Err(why) => eprintln!("Error serializing cliphist content key cache: {}", why),
+    }
+}
+
+fn frecency_score(store: &FrecencyStore, key: u64, half_life_hours: f64, now: u64) -> f64 {
+    match store.get(&key) {
+        Some(entry) => {
+            let age_hours = now.saturating_sub(entry.last_used) as f64 / 3600.0;
+            entry.access_count as f64 * 0.5f64.powf(age_hours / half_life_hours)
         }
+        None => 0.0,
     }
 }
 
@@ -44,45 +204,66 @@ enum Error {
     CliphistReturnCodeError(i32),
     StdinError,
     Threaderror,
+    EditorCommandFailed(std::io::Error),
+    EditorReturnCodeError(i32),
+    TempFileError(std::io::Error),
+    EditLineParseError(String),
+    FileBackendReadError(std::io::Error),
+    FileBackendInvalidId,
+    UnsupportedOperation,
 }
 
 struct State {
     config: Config,
     history: Vec<(usize, String, String)>,
+    config_dir: String,
+    frecency: RefCell<FrecencyStore>,
+    backend: Box<dyn ClipboardBackend>,
+    thumbnails: RefCell<HashMap<String, String>>,
+    content_keys: RefCell<ContentKeyStore>,
 }
 
 #[init]
 fn init(config_dir: RString) -> State {
-    let config: Config = load_config(config_dir);
+    let config_dir = String::from(config_dir);
+    let config: Config = load_config(&config_dir);
 
-    let output = Command::new(&config.cliphist_path)
-        .args(["list"])
-        .output()
-        .map_err(Error::CliphistCommandFailed);
+    let backend = build_backend(config.backend, &config.cliphist_path, &config.history_file);
 
-    let content = match output {
-        Ok(o) => {
-            if o.status.success() {
-                Ok(String::from_utf8_lossy(&o.stdout).into_owned())
-            } else {
-                Err(Error::CliphistReturnCodeError(o.status.code().unwrap_or(1)))
-            }
-        }
-        Err(e) => Err(e),
-    };
+    let history = backend
+        .list()
+        .unwrap_or_else(|why| {
+            eprintln!("Error listing clipboard history: {:?}", why);
+            Vec::new()
+        })
+        .into_iter()
+        .enumerate()
+        .map(|(id, (cliphist_id, entry))| (id, cliphist_id, entry))
+        .collect::<Vec<_>>();
 
-    let history = content.map(|s| {
-        s.split('\n')
-            .filter_map(|l| l.split_once('\t'))
-            .enumerate()
-            .map(|(id, (a, b))| (id, a.to_string(), b.to_string()))
-            .collect::<Vec<_>>()
-    });
+    let live_ids: std::collections::HashSet<&str> =
+        history.iter().map(|(_, cid, _)| cid.as_str()).collect();
 
-    history.map(|history| State { config, history }).unwrap()
+    let mut content_keys = load_content_keys(&config_dir);
+    reconcile_content_keys(&mut content_keys, &live_ids);
+    save_content_keys(&config_dir, &content_keys);
+
+    let mut frecency = load_frecency(&config_dir);
+    reconcile_frecency(&mut frecency, &content_keys);
+    save_frecency(&config_dir, &frecency);
+
+    State {
+        config,
+        history,
+        config_dir,
+        frecency: RefCell::new(frecency),
+        backend,
+        thumbnails: RefCell::new(HashMap::new()),
+        content_keys: RefCell::new(content_keys),
+    }
 }
 
-fn load_config(config_dir: RString) -> Config {
+fn load_config(config_dir: &str) -> Config {
     match fs::read_to_string(format!("{}/cliphist.ron", config_dir)) {
         Ok(content) => ron::from_str(&content).unwrap_or_else(|why| {
             eprintln!("Error parsing cliphist plugin config: {}", why);
@@ -103,6 +284,103 @@ fn info() -> PluginInfo {
     }
 }
 
+const IMAGE_ICON: &str = "image-x-generic-symbolic";
+const TEXT_ICON: &str = "text-x-generic-symbolic";
+
+enum EntryKind {
+    Text,
+    Image(String),
+    Binary(String),
+}
+
+/// cliphist represents non-text entries with a placeholder line like
+/// `[[ binary data 42 KiB image/png ]]`. Pick out the mime type so we can
+/// tag the entry instead of showing the raw placeholder. Matched on the
+/// literal prefix rather than a bare `[[`, since ordinary text can
+/// legitimately start with `[[` (a bash `[[ -d ... ]]` test, a markdown
+/// wiki-link) without being one of cliphist's placeholders.
+fn parse_entry_kind(entry: &str) -> EntryKind {
+    if !entry.trim_start().starts_with("[[ binary data") {
+        return EntryKind::Text;
+    }
+
+    match entry.split_whitespace().find(|tok| tok.contains('/')) {
+        Some(mime) => {
+            let mime = mime.trim_end_matches(']').to_string();
+            if mime.starts_with("image/") {
+                EntryKind::Image(mime)
+            } else {
+                EntryKind::Binary(mime)
+            }
+        }
+        None => EntryKind::Text,
+    }
+}
+
+fn display_label(entry: &str, kind: &EntryKind) -> String {
+    match kind {
+        EntryKind::Text => entry.to_string(),
+        EntryKind::Image(mime) => format!("Image clipping ({})", mime),
+        EntryKind::Binary(mime) => format!("Binary clipping ({})", mime),
+    }
+}
+
+fn thumbnail_path(cliphist_id: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("anyrun-cliphist-thumb-{}.png", cliphist_id))
+}
+
+/// Lazily decodes and caches a PNG thumbnail for an image entry, only ever
+/// called for entries that actually made it into the returned match list.
+fn get_or_create_thumbnail(state: &State, cliphist_id: &str) -> Option<String> {
+    if let Some(path) = state.thumbnails.borrow().get(cliphist_id) {
+        return Some(path.clone());
+    }
+
+    let path = thumbnail_path(cliphist_id);
+    if !path.exists() {
+        let bytes = state.backend.decode(cliphist_id).ok()?;
+        fs::write(&path, bytes).ok()?;
+    }
+
+    let path = path.to_string_lossy().into_owned();
+    state
+        .thumbnails
+        .borrow_mut()
+        .insert(cliphist_id.to_string(), path.clone());
+    Some(path)
+}
+
+fn entry_icon(state: &State, cliphist_id: &str, kind: &EntryKind) -> String {
+    match kind {
+        EntryKind::Image(_) => {
+            get_or_create_thumbnail(state, cliphist_id).unwrap_or_else(|| IMAGE_ICON.to_string())
+        }
+        EntryKind::Binary(_) | EntryKind::Text => TEXT_ICON.to_string(),
+    }
+}
+
+fn escape_pango(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn highlight_matches(entry: &str, indices: &[usize]) -> String {
+    let indices: std::collections::HashSet<usize> = indices.iter().copied().collect();
+    let mut title = String::with_capacity(entry.len());
+    for (i, c) in entry.chars().enumerate() {
+        if indices.contains(&i) {
+            title.push_str("<b>");
+            title.push_str(&escape_pango(&c.to_string()));
+            title.push_str("</b>");
+        } else {
+            title.push_str(&escape_pango(&c.to_string()));
+        }
+    }
+    title
+}
+
 #[get_matches]
 fn get_matches(input: RString, state: &State) -> RVec<Match> {
     if !input.starts_with(&state.config.prefix) {
@@ -110,18 +388,50 @@ fn get_matches(input: RString, state: &State) -> RVec<Match> {
     }
 
     let cleaned_input = &input[state.config.prefix.len()..];
+    if cleaned_input == state.config.edit_keyword {
+        // Bulk-delete needs backend support; don't offer it if it can only
+        // fail (e.g. the file backend, which has no notion of deleting a
+        // line out from under itself).
+        if !state.backend.supports_delete() {
+            return RVec::new();
+        }
+        return RVec::from(vec![Match {
+            title: "Edit clipboard history".into(),
+            description: ROption::RSome("Bulk-delete entries in $EDITOR".into()),
+            use_pango: false,
+            icon: ROption::RNone,
+            id: ROption::RSome(EDIT_MATCH_ID),
+        }]);
+    }
+
+    let frecency = state.frecency.borrow();
+    let now = now_unix();
+
     if cleaned_input.is_empty() {
-        let max_entries = state.history.len().min(state.config.max_entries);
-        let entries = &state.history[..max_entries];
+        let mut entries = state.history.iter().collect::<Vec<_>>();
+        entries.sort_by(|(id_a, cid_a, _), (id_b, cid_b, _)| {
+            let score_a = content_key_for(state, cid_a)
+                .map(|key| frecency_score(&frecency, key, state.config.half_life_hours, now))
+                .unwrap_or(0.0);
+            let score_b = content_key_for(state, cid_b)
+                .map(|key| frecency_score(&frecency, key, state.config.half_life_hours, now))
+                .unwrap_or(0.0);
+            score_b
+                .partial_cmp(&score_a)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| id_a.cmp(id_b))
+        });
+        entries.truncate(state.config.max_entries);
         entries
             .into_iter()
-            .map(|(id, _, entry)| {
-                let title = entry.clone();
+            .map(|(id, cliphist_id, entry)| {
+                let kind = parse_entry_kind(entry);
+                let title = display_label(entry, &kind);
                 Match {
                     title: title.into(),
                     description: ROption::RNone,
                     use_pango: false,
-                    icon: ROption::RNone,
+                    icon: ROption::RSome(entry_icon(state, cliphist_id, &kind).into()),
                     id: ROption::RSome(*id as u64),
                 }
             })
@@ -131,26 +441,38 @@ fn get_matches(input: RString, state: &State) -> RVec<Match> {
         let mut entries = state
             .history
             .iter()
-            .filter_map(|(id, _, entry)| {
-                let score = matcher.fuzzy_match(&entry, cleaned_input).unwrap_or(0);
+            .filter_map(|(id, cliphist_id, entry)| {
+                let (score, indices) = matcher.fuzzy_indices(&entry, cleaned_input)?;
                 if score > 0 {
-                    Some((id, entry, score))
+                    let boost = content_key_for(state, cliphist_id)
+                        .map(|key| {
+                            frecency_score(&frecency, key, state.config.half_life_hours, now)
+                        })
+                        .unwrap_or(0.0);
+                    let combined_score = score as f64 + boost;
+                    Some((id, cliphist_id, entry, combined_score, indices))
                 } else {
                     None
                 }
             })
             .collect::<Vec<_>>();
-        entries.sort_by(|a, b| b.2.cmp(&a.2));
+        entries.sort_by(|a, b| b.3.partial_cmp(&a.3).unwrap_or(std::cmp::Ordering::Equal));
         entries.truncate(state.config.max_entries);
         entries
             .into_iter()
-            .map(|(id, entry, _)| {
-                let title = entry.clone();
+            .map(|(id, cliphist_id, entry, _, indices)| {
+                let kind = parse_entry_kind(entry);
+                let title = match kind {
+                    EntryKind::Text => highlight_matches(entry, &indices),
+                    EntryKind::Image(_) | EntryKind::Binary(_) => {
+                        escape_pango(&display_label(entry, &kind))
+                    }
+                };
                 Match {
                     title: title.into(),
                     description: ROption::RNone,
-                    use_pango: false,
-                    icon: ROption::RNone,
+                    use_pango: true,
+                    icon: ROption::RSome(entry_icon(state, cliphist_id, &kind).into()),
                     id: ROption::RSome(*id as u64),
                 }
             })
@@ -158,9 +480,75 @@ fn get_matches(input: RString, state: &State) -> RVec<Match> {
     }
 }
 
+fn handle_edit(state: &State) -> Result<(), Error> {
+    let path = std::env::temp_dir().join(format!("anyrun-cliphist-edit-{}", std::process::id()));
+
+    let original: String = state
+        .history
+        .iter()
+        .map(|(_, cliphist_id, entry)| format!("{}\t{}\n", cliphist_id, entry))
+        .collect();
+
+    fs::write(&path, &original).map_err(Error::TempFileError)?;
+
+    // anyrun runs without a controlling TTY (launched from a compositor
+    // keybind), so a terminal-based $EDITOR can't be spawned directly -
+    // it needs a terminal emulator to attach to.
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".into());
+    let status = Command::new(&state.config.terminal)
+        .arg("-e")
+        .arg(&editor)
+        .arg(&path)
+        .status()
+        .map_err(Error::EditorCommandFailed)?;
+
+    if !status.success() {
+        let _ = fs::remove_file(&path);
+        return Err(Error::EditorReturnCodeError(status.code().unwrap_or(1)));
+    }
+
+    let edited = fs::read_to_string(&path).map_err(Error::TempFileError)?;
+    let _ = fs::remove_file(&path);
+
+    if edited.trim().is_empty() || edited == original {
+        return Ok(());
+    }
+
+    // A line that doesn't parse (tab replaced by spaces, stray edit, editor
+    // reflow) must not be silently treated as deleted - that would delete an
+    // entry the user never asked to remove. Bail out instead of guessing.
+    let mut surviving_ids: std::collections::HashSet<&str> = std::collections::HashSet::new();
+    for line in edited.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.split_once('\t') {
+            Some((id, _)) => {
+                surviving_ids.insert(id);
+            }
+            None => return Err(Error::EditLineParseError(line.to_string())),
+        }
+    }
+
+    for (_, cliphist_id, _) in &state.history {
+        if !surviving_ids.contains(cliphist_id.as_str()) {
+            state.backend.delete(cliphist_id)?;
+        }
+    }
+
+    Ok(())
+}
+
 #[handler]
 fn handler(selection: Match, state: &State) -> HandleResult {
-    let id = state
+    if selection.id.unwrap() == EDIT_MATCH_ID {
+        if let Err(why) = handle_edit(state) {
+            eprintln!("Error editing cliphist history: {:?}", why);
+        }
+        return HandleResult::Close;
+    }
+
+    let cliphist_id = state
         .history
         .iter()
         .find_map(|(id, cliphist_id, _)| {
@@ -170,35 +558,21 @@ fn handler(selection: Match, state: &State) -> HandleResult {
                 None
             }
         })
-        .map(|id| format!("{}\t ", id))
         .unwrap();
 
-    let child = Command::new(&state.config.cliphist_path)
-        .args(["decode"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .map_err(Error::CliphistCommandFailed);
-
-    let output = child.and_then(|mut c| {
-        let write_to_stdin = c
-            .stdin
-            .take()
-            .ok_or(Error::StdinError)
-            .and_then(|mut stdin| {
-                std::thread::spawn(move || {
-                    stdin
-                        .write_all(id.as_bytes())
-                        .map_err(|_| Error::StdinError)
-                })
-                .join()
-                .map_err(|_| Error::Threaderror)
-                .and_then(|r| r)
-            });
-        write_to_stdin.and_then(|_| c.wait_with_output().map_err(Error::CliphistCommandFailed))
-    });
-
-    output
-        .map(|bytes| HandleResult::Copy(bytes.stdout.into()))
-        .unwrap()
+    let stdout = state.backend.decode(cliphist_id).unwrap();
+    let key = content_key(&stdout);
+    {
+        let mut content_keys = state.content_keys.borrow_mut();
+        content_keys.insert(cliphist_id.to_string(), key);
+        save_content_keys(&state.config_dir, &content_keys);
+    }
+
+    let mut frecency = state.frecency.borrow_mut();
+    let usage = frecency.entry(key).or_default();
+    usage.access_count += 1;
+    usage.last_used = now_unix();
+    save_frecency(&state.config_dir, &frecency);
+
+    HandleResult::Copy(stdout.into())
 }